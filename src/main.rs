@@ -124,31 +124,129 @@ impl std::fmt::Display for Board {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseBoardError;
+
+impl std::fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid board string")
+    }
+}
+impl std::error::Error for ParseBoardError {}
+
+impl std::str::FromStr for Board {
+    type Err = ParseBoardError;
+    // parses the Display format; recomputes the hash rather than rebuilding it incrementally
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cells: Vec<char> = s.lines().filter(|l| *l != "===").flat_map(|l| l.chars()).collect();
+        if cells.len() != WIDTH * HEIGHT {
+            return Err(ParseBoardError);
+        }
+        let mut b = Board::new();
+        for (i, ch) in cells.into_iter().enumerate() {
+            let (nonempty, player, flipped) = match ch {
+                '.' => (false, false, false),
+                'x' => (true, false, false),
+                'o' => (true, true, false),
+                'X' => (true, false, true),
+                'O' => (true, true, true),
+                _ => return Err(ParseBoardError),
+            };
+            if nonempty {
+                b.nonempty |= 1 << i;
+                if player { b.player |= 1 << i; }
+                if flipped { b.flipped |= 1 << i; }
+            }
+        }
+        b.hash = b.hash();
+        Ok(b)
+    }
+}
+
+// whether a TT entry is the exact score, or just a bound from an alpha-beta cutoff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Exact,
+    LowerBound,
+    UpperBound,
+}
+
+#[derive(Debug, Clone)]
+struct TTEntry {
+    depth: i32,
+    value: i32,
+    flag: Flag,
+    best: Option<Board>,
+}
+
 #[derive(Default)]
 pub struct Solver {
-    cache: ahash::AHashMap<BoardHash, (i32, Option<Board>)>,
-    old_cache: ahash::AHashMap<BoardHash, (i32, Option<Board>)>,
+    cache: ahash::AHashMap<BoardHash, TTEntry>,
+    old_cache: ahash::AHashMap<BoardHash, TTEntry>,
     use_old_cache: bool,
+    nodes: u64,
+    deadline: Option<std::time::Instant>,
+    timed_out: bool,
 }
 
 const PLAYER_HASH: u64 = 0x1337;
 impl Solver {
-    pub fn minimax(&mut self, b: &Board, player: usize, depth: i32, mut alpha: i32, beta: i32) -> (i32,Option<Board>) {
+    pub fn minimax(&mut self, b: &Board, player: usize, depth: i32, mut alpha: i32, mut beta: i32) -> (i32,Option<Board>) {
         debug_assert!(b.hash() == b.hash);
         //assert!(b.score() == b.score);
         if depth == 0 {
             return (b.score() * (1 - 2 * player as i32), None);
         }
+        // under a time budget, check the clock every so often rather than on
+        // every node, so the check itself doesn't dominate
+        self.nodes += 1;
+        if let Some(deadline) = self.deadline {
+            if self.nodes % 4096 == 0 && std::time::Instant::now() >= deadline {
+                self.timed_out = true;
+            }
+            if self.timed_out {
+                return (b.score() * (1 - 2 * player as i32), None);
+            }
+        }
+        let alpha_orig = alpha;
+        let beta_orig = beta;
         let bhash = b.hash ^ PLAYER_HASH*(player as u64);
-        // if we've seen this state on this iteration...
-        if let Some((i,b)) = self.cache.get(&bhash) { return (*i,b.clone()); }
+        // if we've seen this state on this iteration, at least as deep as we need now...
+        if let Some(entry) = self.cache.get(&bhash) {
+            if entry.depth >= depth {
+                match entry.flag {
+                    Flag::Exact => return (entry.value, entry.best.clone()),
+                    Flag::LowerBound => alpha = alpha.max(entry.value),
+                    Flag::UpperBound => beta = beta.min(entry.value),
+                }
+                if alpha >= beta {
+                    return (entry.value, entry.best.clone());
+                }
+            }
+        }
         let mut best_so_far = 0;
         let mut best_board = None;
         // if we have a previous best, check it first
-        let prev_best = self.old_cache.get(&bhash).and_then(|v| v.1.clone());
+        let prev_best = self.old_cache.get(&bhash).and_then(|v| v.best.clone());
         let the_iter = prev_best.iter().cloned().chain(b.clone().moves(player).filter(|x| Some(x) != prev_best.as_ref()));
+        // Principal Variation Search: the first move (the best guess, from
+        // prev_best if we have one) gets a full-window search; every move
+        // after that gets a cheap null-window "scout" search, which is only
+        // re-searched with the full window if it turns out to beat alpha
+        // without also beating beta (i.e. it might actually be the new PV).
+        let mut first = true;
         for board in the_iter {
-            let s = -self.minimax(&board, player^1, depth-1, -beta, -alpha).0;
+            let s = if first {
+                -self.minimax(&board, player^1, depth-1, -beta, -alpha).0
+            } else {
+                let scout = -self.minimax(&board, player^1, depth-1, -alpha-1, -alpha).0;
+                if scout > alpha && scout < beta {
+                    -self.minimax(&board, player^1, depth-1, -beta, -alpha).0
+                } else {
+                    scout
+                }
+            };
+            first = false;
             if best_board.is_none() || s > best_so_far {
                 best_so_far = s;
                 best_board = Some(board);
@@ -157,7 +255,14 @@ impl Solver {
             if alpha >= beta { break; }
         }
         if best_board.is_none() { best_so_far = b.score(); }
-        self.cache.insert(bhash, (best_so_far, best_board.clone()));
+        let flag = if best_so_far <= alpha_orig {
+            Flag::UpperBound
+        } else if best_so_far >= beta_orig {
+            Flag::LowerBound
+        } else {
+            Flag::Exact
+        };
+        self.cache.insert(bhash, TTEntry { depth, value: best_so_far, flag, best: best_board.clone() });
         (best_so_far,best_board)
     }
 
@@ -175,6 +280,115 @@ impl Solver {
         self.use_old_cache = true;
         res
     }
+
+    /// like `solve`, but deepens under a time budget instead of a fixed depth, returning the best move from the
+    /// last fully completed depth (`None` if no depth completed in time)
+    pub fn solve_timed(&mut self, b: &Board, player: usize, budget: std::time::Duration) -> Option<Board> {
+        let deadline = std::time::Instant::now() + budget;
+        self.deadline = Some(deadline);
+        self.nodes = 0;
+        let mut res = None;
+        let mut depth = if self.use_old_cache { 1 } else { 4 };
+        loop {
+            if std::time::Instant::now() >= deadline { break; }
+            self.timed_out = false;
+            let (_s, br) = self.minimax(&b, player, depth, -i32::MAX, i32::MAX);
+            if self.timed_out {
+                // entries inserted while aborting this depth are poisoned
+                // (static-eval short-circuit values stored as if they were
+                // real search results) - discard them rather than let a
+                // later call read them back as legitimate TT hits
+                self.cache.clear();
+                break;
+            }
+            res = br;
+            std::mem::swap(&mut self.cache, &mut self.old_cache);
+            self.cache.clear();
+            depth += 1;
+        }
+        self.deadline = None;
+        self.use_old_cache = true;
+        res
+    }
+
+    /// entry point for `ybwc_minimax`'s parallel search, spread across `threads`
+    pub fn solve_parallel(&self, b: &Board, player: usize, threads: usize) -> Option<Board> {
+        const FULLDEPTH: i32 = 7;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("failed to build thread pool");
+        let cache: ConcurrentCache = dashmap::DashMap::new();
+        pool.install(|| ybwc_minimax(b, player, FULLDEPTH, -i32::MAX, i32::MAX, &cache, &self.old_cache).1)
+    }
+}
+
+type ConcurrentCache = dashmap::DashMap<BoardHash, TTEntry>;
+
+// Young Brothers Wait: the eldest sibling is searched sequentially first to tighten alpha, then the
+// rest run via rayon off that shared bound. Uses a dashmap since self.cache needs &mut self to reach.
+fn ybwc_minimax(b: &Board, player: usize, depth: i32, alpha: i32, beta: i32, cache: &ConcurrentCache, old_cache: &ahash::AHashMap<BoardHash, TTEntry>) -> (i32, Option<Board>) {
+    if depth == 0 {
+        return (b.score() * (1 - 2 * player as i32), None);
+    }
+    let alpha_orig = alpha;
+    let beta_orig = beta;
+    let mut alpha = alpha;
+    let mut beta = beta;
+    let bhash = b.hash ^ PLAYER_HASH*(player as u64);
+    if let Some(entry) = cache.get(&bhash) {
+        if entry.depth >= depth {
+            match entry.flag {
+                Flag::Exact => return (entry.value, entry.best.clone()),
+                Flag::LowerBound => alpha = alpha.max(entry.value),
+                Flag::UpperBound => beta = beta.min(entry.value),
+            }
+            if alpha >= beta {
+                return (entry.value, entry.best.clone());
+            }
+        }
+    }
+    let prev_best = old_cache.get(&bhash).and_then(|v| v.best.clone());
+    let mut moves: Vec<Board> = b.clone().moves(player).collect();
+    if let Some(pb) = &prev_best {
+        if let Some(pos) = moves.iter().position(|m| m == pb) {
+            moves.swap(0, pos);
+        }
+    }
+    if moves.is_empty() {
+        let s = b.score();
+        cache.insert(bhash, TTEntry { depth, value: s, flag: Flag::Exact, best: None });
+        return (s, None);
+    }
+    let eldest = moves.remove(0);
+    let eldest_score = -ybwc_minimax(&eldest, player^1, depth-1, -beta, -alpha, cache, old_cache).0;
+    let shared_alpha = std::sync::atomic::AtomicI32::new(alpha.max(eldest_score));
+    let best = std::sync::Mutex::new((eldest_score, Some(eldest)));
+    if shared_alpha.load(std::sync::atomic::Ordering::SeqCst) < beta {
+        use rayon::prelude::*;
+        moves.into_par_iter().for_each(|board| {
+            let a = shared_alpha.load(std::sync::atomic::Ordering::SeqCst);
+            if a >= beta { return; }
+            let s = -ybwc_minimax(&board, player^1, depth-1, -beta, -a, cache, old_cache).0;
+            if s > a {
+                shared_alpha.fetch_max(s, std::sync::atomic::Ordering::SeqCst);
+            }
+            let mut best_guard = best.lock().unwrap();
+            if s > best_guard.0 {
+                *best_guard = (s, Some(board));
+            }
+        });
+    }
+    let (best_so_far, best_board) = best.into_inner().unwrap();
+    let flag = if best_so_far <= alpha_orig {
+        Flag::UpperBound
+    } else if best_so_far >= beta_orig {
+        Flag::LowerBound
+    } else {
+        Flag::Exact
+    };
+    cache.insert(bhash, TTEntry { depth, value: best_so_far, flag, best: best_board.clone() });
+    (best_so_far, best_board)
 }
 
 fn init_zobrist() {
@@ -187,25 +401,209 @@ fn init_zobrist() {
     }
 }
 
+/// How a `Game` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    /// the player to move had no legal moves left
+    NoMoves,
+    /// a position (board hash + side to move) repeated `repetition_limit` times
+    Draw,
+}
+
+/// Drives a single game to completion, declaring a draw once a position (board hash + side to move) has occurred
+/// `repetition_limit` times
+pub struct Game {
+    board: Board,
+    turn: u32,
+    seen: std::collections::HashMap<(BoardHash, usize), u32>,
+    repetition_limit: u32,
+}
+
+impl Game {
+    pub fn new(repetition_limit: u32) -> Self {
+        Self { board: Board::new(), turn: 0, seen: std::collections::HashMap::new(), repetition_limit }
+    }
+
+    pub fn board(&self) -> &Board { &self.board }
+    pub fn turn(&self) -> u32 { self.turn }
+
+    /// Plays one move chosen by `solver`; returns `None` until the game ends.
+    pub fn step(&mut self, solver: &mut Solver) -> Option<GameResult> {
+        let player = (self.turn & 1) as usize;
+        let Some(next) = solver.solve(&self.board, player) else {
+            return Some(GameResult::NoMoves);
+        };
+        self.board = next;
+        self.turn += 1;
+        let key = (self.board.hash, (self.turn & 1) as usize);
+        let count = self.seen.entry(key).or_insert(0);
+        *count += 1;
+        if *count >= self.repetition_limit {
+            return Some(GameResult::Draw);
+        }
+        None
+    }
+}
+
 fn main() {
-    use rand::seq::SliceRandom;
-    let mut rng = rand::thread_rng();
-    let mut b = Board::new();
     let mut solver = Solver::default();
-    let mut turn = 0;
+    let mut game = Game::new(3);
     init_zobrist();
     loop {
-        if false && turn&1 == 1 {
-            let moves: Vec<_> = b.moves(turn&1).collect();
-            if moves.len() == 0 { return; }
-            b = moves.choose(&mut rng).unwrap().clone();
-        } else {
-            let br = solver.solve(&b, turn&1);
-            if br.is_none() { return; }
-            b = br.unwrap();
+        let result = game.step(&mut solver);
+        println!("{}", game.board());
+        println!("Score {:?}", game.board().score());
+        if let Some(result) = result {
+            println!("Game over: {:?}", result);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+
+    // a depth-1 TT entry must never be reused to answer a deeper query
+    #[test]
+    fn shallow_tt_entry_is_not_reused_for_deeper_search() {
+        init_zobrist();
+        let b = Board::new();
+        let mut solver = Solver::default();
+        solver.minimax(&b, 0, 1, -i32::MAX, i32::MAX);
+        let with_shallow_cache = solver.minimax(&b, 0, 5, -i32::MAX, i32::MAX);
+        let mut fresh_solver = Solver::default();
+        let fresh = fresh_solver.minimax(&b, 0, 5, -i32::MAX, i32::MAX);
+        assert_eq!(with_shallow_cache.0, fresh.0, "a depth-1 TT entry must not be reused for a depth-5 search");
+    }
+
+    // YBWC's shared-cache search must return one of the root's actual legal moves, without panicking
+    #[test]
+    fn ybwc_parallel_search_returns_a_legal_move() {
+        init_zobrist();
+        let b = Board::new();
+        let cache: ConcurrentCache = dashmap::DashMap::new();
+        let old_cache = ahash::AHashMap::new();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let (_, best) = pool.install(|| ybwc_minimax(&b, 0, 2, -i32::MAX, i32::MAX, &cache, &old_cache));
+        let best = best.expect("starting board has legal moves");
+        assert!(b.clone().moves(0).any(|m| m == best), "returned move must be one of the board's legal moves");
+    }
+
+    #[test]
+    fn solve_timed_returns_a_legal_move() {
+        init_zobrist();
+        let b = Board::new();
+        let mut solver = Solver::default();
+        let best = solver.solve_timed(&b, 0, std::time::Duration::from_millis(50));
+        let best = best.expect("starting board has legal moves");
+        assert!(b.clone().moves(0).any(|m| m == best), "returned move must be one of the board's legal moves");
+    }
+
+    // regression for a bug where an aborted depth left partially-searched,
+    // degenerate-score entries in self.cache for later calls to read back as real TT hits
+    #[test]
+    fn solve_timed_timeout_does_not_poison_cache() {
+        init_zobrist();
+        let b = Board::new();
+        let mut solver = Solver::default();
+        solver.solve_timed(&b, 0, std::time::Duration::from_micros(1));
+        if solver.timed_out {
+            assert!(solver.cache.is_empty(), "an aborted depth must not leave poisoned TT entries behind");
+        }
+        // a solver that's been through a timed-out search must still answer
+        // a plain query from the same root exactly like a fresh one would
+        let mut fresh = Solver::default();
+        let after_timeout = solver.minimax(&b, 0, 1, -i32::MAX, i32::MAX);
+        let from_fresh = fresh.minimax(&b, 0, 1, -i32::MAX, i32::MAX);
+        assert_eq!(after_timeout.0, from_fresh.0);
+        assert_eq!(after_timeout.1.is_some(), from_fresh.1.is_some());
+    }
+
+    // plain alpha-beta with no scout/re-search, used as a reference to check PVS against
+    fn plain_alpha_beta(b: &Board, player: usize, depth: i32, mut alpha: i32, beta: i32) -> i32 {
+        if depth == 0 {
+            return b.score() * (1 - 2 * player as i32);
+        }
+        let mut best = None;
+        for board in b.clone().moves(player) {
+            let s = -plain_alpha_beta(&board, player^1, depth-1, -beta, -alpha);
+            best = Some(match best { Some(x) => if s > x { s } else { x }, None => s });
+            alpha = alpha.max(s);
+            if alpha >= beta { break; }
+        }
+        best.unwrap_or_else(|| b.score())
+    }
+
+    #[test]
+    fn pvs_matches_plain_full_window_search_at_low_depth() {
+        init_zobrist();
+        let mut rng = rand::thread_rng();
+        for _ in 0..5 {
+            let mut b = Board::new();
+            for ply in 0..3 {
+                let player = ply & 1;
+                let moves: Vec<Board> = b.clone().moves(player).collect();
+                let Some(next) = moves.choose(&mut rng) else { break; };
+                b = next.clone();
+            }
+            let mut solver = Solver::default();
+            let (pvs_score, _) = solver.minimax(&b, 0, 3, -i32::MAX, i32::MAX);
+            let plain_score = plain_alpha_beta(&b, 0, 3, -i32::MAX, i32::MAX);
+            assert_eq!(pvs_score, plain_score, "PVS must find the same score as a plain full-window search");
+        }
+    }
+
+    #[test]
+    fn game_step_reports_no_moves_when_board_is_full_and_flipped() {
+        init_zobrist();
+        let mut full = Board::new();
+        full.nonempty = (1u32 << 25) - 1;
+        full.flipped = full.nonempty;
+        full.hash = full.hash();
+        let mut game = Game::new(3);
+        game.board = full;
+        let mut solver = Solver::default();
+        assert_eq!(game.step(&mut solver), Some(GameResult::NoMoves));
+    }
+
+    #[test]
+    fn game_step_declares_draw_after_forced_repetition() {
+        init_zobrist();
+        let mut almost_full = Board::new();
+        almost_full.nonempty = ((1u32 << 25) - 1) & !1;
+        almost_full.flipped = almost_full.nonempty;
+        almost_full.hash = almost_full.hash();
+        let player = 0usize;
+        let next = almost_full.clone().moves(player).next().expect("exactly one legal move (the one empty cell)");
+        let key = (next.hash, player ^ 1);
+
+        let mut game = Game::new(2); // draw on the 2nd occurrence of a position
+        game.board = almost_full;
+        game.seen.insert(key, 1); // pretend this position has already been seen once
+
+        let mut solver = Solver::default();
+        assert_eq!(game.step(&mut solver), Some(GameResult::Draw));
+    }
+
+    // plays random legal moves, checking the incremental hash against both a recompute and a roundtrip each time
+    #[test]
+    fn incremental_hash_matches_recompute_and_roundtrip() {
+        init_zobrist();
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let mut b = Board::new();
+            for ply in 0..12 {
+                let player = ply & 1;
+                let moves: Vec<Board> = b.clone().moves(player).collect();
+                let Some(next) = moves.choose(&mut rng) else { break; };
+                b = next.clone();
+                assert_eq!(b.hash, b.hash(), "incremental hash desynced from recompute");
+                let roundtrip: Board = b.to_string().parse().expect("board should round-trip through Display/FromStr");
+                assert_eq!(b.hash, roundtrip.hash(), "roundtrip hash doesn't match recompute");
+                assert_eq!(b, roundtrip, "roundtrip board doesn't match original");
+            }
         }
-        turn += 1;
-        println!("{}", b);
-        println!("Score {:?}", b.score());
     }
 }